@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use solana_sdk::native_token::{lamports_to_sol, LAMPORTS_PER_SOL};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Bid {
+    pub bid_amount_dollars: f64,
+    pub primary_address: String,
+    pub lockup_date: Option<String>,
+}
+
+/// An allocation straight off the CSV: unvalidated strings, kept around for test
+/// helpers that want to build one without a real pubkey on hand.
+pub struct Allocation {
+    pub recipient: String,
+    pub amount: f64,
+    pub lockup_date: Option<String>,
+}
+
+/// An allocation that has passed validation and is ready to be turned into an
+/// instruction: `recipient` is a real `Pubkey` and `amount` is already in the
+/// chain's native unit (lamports, or raw SPL token amount).
+pub struct TypedAllocation {
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub lockup_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Csv(csv::Error),
+    EmptyCsv,
+    BadPubkey { row: usize, input: String },
+    BadLockupDate { row: usize, input: String },
+    BadMint { input: Pubkey },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Csv(err) => write!(f, "{}", err),
+            Error::EmptyCsv => write!(f, "allocations CSV has no rows"),
+            Error::BadPubkey { row, input } => {
+                write!(f, "row {}: {:?} is not a valid pubkey", row, input)
+            }
+            Error::BadLockupDate { row, input } => write!(
+                f,
+                "row {}: {:?} is not a valid RFC3339 lockup_date",
+                row, input
+            ),
+            Error::BadMint { input } => write!(f, "{} is not a valid token mint", input),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Csv(err)
+    }
+}
+
+fn create_allocation(bid: &Bid, dollars_per_sol: f64) -> Allocation {
+    Allocation {
+        recipient: bid.primary_address.clone(),
+        amount: bid.bid_amount_dollars / dollars_per_sol,
+        lockup_date: bid.lockup_date.clone(),
+    }
+}
+
+// SPL amounts are already denominated in the token's UI units, so there's no
+// dollars_per_sol conversion to apply.
+fn create_token_allocation(bid: &Bid) -> Allocation {
+    Allocation {
+        recipient: bid.primary_address.clone(),
+        amount: bid.bid_amount_dollars,
+        lockup_date: None,
+    }
+}
+
+pub fn allocations_from_bids(
+    bids: &[Bid],
+    dollars_per_sol: f64,
+    is_token: bool,
+) -> Vec<Allocation> {
+    bids.iter()
+        .map(|bid| {
+            if is_token {
+                create_token_allocation(bid)
+            } else {
+                create_allocation(bid, dollars_per_sol)
+            }
+        })
+        .collect()
+}
+
+/// A minimal CSV row for `--transfer-amount` mode, where every recipient gets the
+/// same flat amount and there's no per-row dollar figure to carry.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Recipient {
+    pub recipient: String,
+}
+
+pub fn allocations_from_recipients(recipients: &[Recipient], amount: f64) -> Vec<Allocation> {
+    recipients
+        .iter()
+        .map(|recipient| Allocation {
+            recipient: recipient.recipient.clone(),
+            amount,
+            lockup_date: None,
+        })
+        .collect()
+}
+
+/// Converts a human-readable amount (SOL, or a token's UI units) into the chain's
+/// native unit, given the token's `decimals` (`None` for native SOL).
+pub fn raw_amount(amount: f64, decimals: Option<u8>) -> u64 {
+    match decimals {
+        Some(decimals) => (amount * 10f64.powi(decimals as i32)).round() as u64,
+        None => (amount * LAMPORTS_PER_SOL as f64).round() as u64,
+    }
+}
+
+/// The inverse of `raw_amount`, for display and for comparing against on-chain balances.
+pub fn display_amount(amount: u64, decimals: Option<u8>) -> f64 {
+    match decimals {
+        Some(decimals) => amount as f64 / 10f64.powi(decimals as i32),
+        None => lamports_to_sol(amount),
+    }
+}
+
+/// Validates and parses every row up front, so a single malformed pubkey or
+/// lockup_date in a multi-thousand-row CSV surfaces as a row-level error instead
+/// of a panic partway through a distribution.
+pub fn parse_typed_allocations(
+    allocations: &[Allocation],
+    decimals: Option<u8>,
+) -> Result<Vec<TypedAllocation>, Error> {
+    if allocations.is_empty() {
+        return Err(Error::EmptyCsv);
+    }
+    allocations
+        .iter()
+        .enumerate()
+        .map(|(row, allocation)| {
+            let recipient = allocation.recipient.parse().map_err(|_| Error::BadPubkey {
+                row,
+                input: allocation.recipient.clone(),
+            })?;
+            let lockup_date = allocation
+                .lockup_date
+                .as_ref()
+                .map(|date| {
+                    date.parse().map_err(|_| Error::BadLockupDate {
+                        row,
+                        input: date.clone(),
+                    })
+                })
+                .transpose()?;
+            Ok(TypedAllocation {
+                recipient,
+                amount: raw_amount(allocation.amount, decimals),
+                lockup_date,
+            })
+        })
+        .collect()
+}
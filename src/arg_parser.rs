@@ -0,0 +1,183 @@
+use clap::{crate_description, crate_name, crate_version, App, Arg, ArgMatches, SubCommand};
+use std::ffi::OsString;
+
+pub struct CommandArgs {
+    pub config_file: String,
+    pub url: Option<String>,
+    pub command: ArgMatches<'static>,
+}
+
+fn common_distribute_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("allocations_csv")
+            .long("input-csv")
+            .takes_value(true)
+            .value_name("FILEPATH")
+            .required(true)
+            .help("CSV file with recipient allocations"),
+        Arg::with_name("transaction_db")
+            .long("transaction-db")
+            .takes_value(true)
+            .value_name("FILEPATH")
+            .required(true)
+            .help("Key-value store of submitted transactions, for resuming a run"),
+        Arg::with_name("transaction_log")
+            .long("transaction-log")
+            .takes_value(true)
+            .value_name("FILEPATH")
+            .help("Dump the transaction db to this CSV path once the run completes"),
+        Arg::with_name("from")
+            .long("from")
+            .takes_value(true)
+            .value_name("SIGNER")
+            .required(true)
+            .help("Signer for the sender: a keypair file, or a signer URI such as usb://ledger"),
+        Arg::with_name("fee_payer")
+            .long("fee-payer")
+            .takes_value(true)
+            .value_name("SIGNER")
+            .required(true)
+            .help("Signer for the transaction fee payer: a keypair file, or a signer URI such as usb://ledger"),
+        Arg::with_name("dry_run")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the distribution without sending transactions"),
+        Arg::with_name("commitment")
+            .long("commitment")
+            .takes_value(true)
+            .value_name("COMMITMENT")
+            .default_value("finalized")
+            .help("Commitment level a transaction must reach before it's considered landed"),
+        Arg::with_name("transfer_amount")
+            .long("transfer-amount")
+            .takes_value(true)
+            .value_name("SOL")
+            .help("Send this fixed amount to every recipient instead of reading per-row bid amounts; the input CSV only needs a `recipient` column"),
+    ]
+}
+
+pub fn parse_args<I, T>(args: I) -> CommandArgs
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let matches = App::new(crate_name!())
+        .about(crate_description!())
+        .version(crate_version!())
+        .arg(
+            Arg::with_name("config_file")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILEPATH")
+                .help("Configuration file to use"),
+        )
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .takes_value(true)
+                .value_name("URL")
+                .help("JSON RPC URL for the cluster"),
+        )
+        .subcommand(
+            SubCommand::with_name("distribute-tokens")
+                .about("Distribute SOL, or an SPL token, to a list of recipients")
+                .args(&common_distribute_args())
+                .arg(
+                    Arg::with_name("dollars_per_sol")
+                        .long("dollars-per-sol")
+                        .takes_value(true)
+                        .value_name("FLOAT")
+                        .required_unless_one(&["token", "transfer_amount"])
+                        .help("Dollars per SOL, used to convert bid amounts to SOL"),
+                )
+                .arg(
+                    Arg::with_name("token")
+                        .long("token")
+                        .takes_value(true)
+                        .value_name("MINT_PUBKEY")
+                        .help("Distribute an SPL token instead of native SOL; CSV amounts are in the token's UI units"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("distribute-stake")
+                .about("Fund and delegate a new stake account per recipient")
+                .args(&common_distribute_args())
+                .arg(
+                    Arg::with_name("dollars_per_sol")
+                        .long("dollars-per-sol")
+                        .takes_value(true)
+                        .value_name("FLOAT")
+                        .required_unless("transfer_amount")
+                        .help("Dollars per SOL, used to convert bid amounts to SOL"),
+                )
+                .arg(
+                    Arg::with_name("stake_authority")
+                        .long("stake-authority")
+                        .takes_value(true)
+                        .value_name("PUBKEY")
+                        .required(true)
+                        .help("Stake authority for each new stake account"),
+                )
+                .arg(
+                    Arg::with_name("withdraw_authority")
+                        .long("withdraw-authority")
+                        .takes_value(true)
+                        .value_name("PUBKEY")
+                        .required(true)
+                        .help("Withdraw authority for each new stake account"),
+                )
+                .arg(
+                    Arg::with_name("vote_account")
+                        .long("vote-account")
+                        .takes_value(true)
+                        .value_name("PUBKEY")
+                        .required(true)
+                        .help("Vote account each new stake account is delegated to"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("balances")
+                .about("Check recipient balances against an allocations CSV")
+                .arg(
+                    Arg::with_name("allocations_csv")
+                        .long("input-csv")
+                        .takes_value(true)
+                        .value_name("FILEPATH")
+                        .required(true)
+                        .help("CSV file with recipient allocations"),
+                )
+                .arg(
+                    Arg::with_name("dollars_per_sol")
+                        .long("dollars-per-sol")
+                        .takes_value(true)
+                        .value_name("FLOAT")
+                        .required_unless_one(&["token", "transfer_amount"])
+                        .help("Dollars per SOL, used to convert bid amounts to SOL"),
+                )
+                .arg(
+                    Arg::with_name("token")
+                        .long("token")
+                        .takes_value(true)
+                        .value_name("MINT_PUBKEY")
+                        .help("Check an SPL token balance instead of native SOL"),
+                )
+                .arg(
+                    Arg::with_name("transfer_amount")
+                        .long("transfer-amount")
+                        .takes_value(true)
+                        .value_name("SOL")
+                        .help("Expected flat amount every recipient was sent, for auditing a `--transfer-amount` distribution; the input CSV only needs a `recipient` column"),
+                ),
+        )
+        .get_matches_from(args);
+
+    let default_config_file = solana_cli_config::CONFIG_FILE.as_ref().unwrap();
+    CommandArgs {
+        config_file: matches
+            .value_of("config_file")
+            .unwrap_or(default_config_file)
+            .to_string(),
+        url: matches.value_of("url").map(|s| s.to_string()),
+        command: matches,
+    }
+}
@@ -0,0 +1,182 @@
+use clap::ArgMatches;
+use solana_clap_utils::keypair::signer_from_path;
+use solana_remote_wallet::remote_wallet::maybe_wallet_manager;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::error::Error;
+use std::str::FromStr;
+
+pub struct StakeArgs {
+    pub stake_authority: Pubkey,
+    pub withdraw_authority: Pubkey,
+    pub vote_account: Pubkey,
+}
+
+pub struct DistributeArgs<S> {
+    pub sender_keypair: Option<S>,
+    pub fee_payer: Option<S>,
+    pub allocations_csv: String,
+    pub transaction_db: String,
+    /// If set, the transaction db is dumped to this CSV path once the run completes.
+    pub transaction_log: Option<String>,
+    pub dollars_per_sol: f64,
+    /// Flat amount (SOL, or the token's UI units) to send every recipient, bypassing
+    /// the `Bid`/`dollars_per_sol` conversion; the allocations CSV only needs a
+    /// `recipient` column when this is set.
+    pub transfer_amount: Option<f64>,
+    pub dry_run: bool,
+    /// Mint to distribute instead of native SOL, when set.
+    pub token_mint: Option<Pubkey>,
+    /// Fund and delegate a new stake account per allocation instead of a bare transfer.
+    pub stake_args: Option<StakeArgs>,
+    /// Commitment level a transaction must reach before it is considered finalized.
+    pub commitment: CommitmentConfig,
+}
+
+pub struct BalancesArgs {
+    pub allocations_csv: String,
+    pub dollars_per_sol: f64,
+    pub token_mint: Option<Pubkey>,
+    /// Flat amount (SOL, or the token's UI units) every recipient was expected to
+    /// receive, for auditing a distribution made with `--transfer-amount`; the
+    /// allocations CSV only needs a `recipient` column when this is set.
+    pub transfer_amount: Option<f64>,
+}
+
+pub enum Command {
+    Distribute(DistributeArgs<Box<dyn Signer>>),
+    Balances(BalancesArgs),
+}
+
+struct CommonDistributeArgs {
+    allocations_csv: String,
+    transaction_db: String,
+    transaction_log: Option<String>,
+    transfer_amount: Option<f64>,
+    dry_run: bool,
+    sender_keypair: Box<dyn Signer>,
+    fee_payer: Box<dyn Signer>,
+    commitment: CommitmentConfig,
+}
+
+fn parse_common_distribute_args(
+    matches: &ArgMatches,
+) -> Result<CommonDistributeArgs, Box<dyn Error>> {
+    let allocations_csv = value_t!(matches, "allocations_csv", String)?;
+    let transaction_db = value_t!(matches, "transaction_db", String)?;
+    let transaction_log = value_t!(matches, "transaction_log", String).ok();
+    let transfer_amount = value_t!(matches, "transfer_amount", f64).ok();
+    let dry_run = matches.is_present("dry_run");
+    let from = value_t!(matches, "from", String)?;
+    let fee_payer = value_t!(matches, "fee_payer", String)?;
+    let commitment = CommitmentConfig::from_str(&value_t!(matches, "commitment", String)?)?;
+
+    // Shared across both signers so a hardware wallet (e.g. `usb://ledger`) is only
+    // unlocked once per run, not once per `--from`/`--fee-payer` argument.
+    let mut wallet_manager = maybe_wallet_manager()?;
+    let sender_keypair = signer_from_path(matches, &from, "from", &mut wallet_manager)?;
+    let fee_payer = signer_from_path(matches, &fee_payer, "fee_payer", &mut wallet_manager)?;
+
+    Ok(CommonDistributeArgs {
+        allocations_csv,
+        transaction_db,
+        transaction_log,
+        transfer_amount,
+        dry_run,
+        sender_keypair,
+        fee_payer,
+        commitment,
+    })
+}
+
+fn parse_distribute_args(
+    matches: &ArgMatches,
+) -> Result<DistributeArgs<Box<dyn Signer>>, Box<dyn Error>> {
+    let common = parse_common_distribute_args(matches)?;
+    let token_mint = value_t!(matches, "token", Pubkey).ok();
+
+    // `--dollars-per-sol` only makes sense for native SOL distributions; an SPL
+    // distribution's CSV amounts are already denominated in the token's UI units.
+    // `--transfer-amount` bypasses the conversion entirely, so it's unused either way.
+    let dollars_per_sol = if common.transfer_amount.is_some() || token_mint.is_some() {
+        value_t!(matches, "dollars_per_sol", f64).unwrap_or(1.0)
+    } else {
+        value_t!(matches, "dollars_per_sol", f64)?
+    };
+
+    Ok(DistributeArgs {
+        sender_keypair: Some(common.sender_keypair),
+        fee_payer: Some(common.fee_payer),
+        allocations_csv: common.allocations_csv,
+        transaction_db: common.transaction_db,
+        transaction_log: common.transaction_log,
+        dollars_per_sol,
+        transfer_amount: common.transfer_amount,
+        dry_run: common.dry_run,
+        token_mint,
+        stake_args: None,
+        commitment: common.commitment,
+    })
+}
+
+fn parse_distribute_stake_args(
+    matches: &ArgMatches,
+) -> Result<DistributeArgs<Box<dyn Signer>>, Box<dyn Error>> {
+    let common = parse_common_distribute_args(matches)?;
+    let dollars_per_sol = if common.transfer_amount.is_some() {
+        value_t!(matches, "dollars_per_sol", f64).unwrap_or(1.0)
+    } else {
+        value_t!(matches, "dollars_per_sol", f64)?
+    };
+    let stake_args = StakeArgs {
+        stake_authority: value_t!(matches, "stake_authority", Pubkey)?,
+        withdraw_authority: value_t!(matches, "withdraw_authority", Pubkey)?,
+        vote_account: value_t!(matches, "vote_account", Pubkey)?,
+    };
+
+    Ok(DistributeArgs {
+        sender_keypair: Some(common.sender_keypair),
+        fee_payer: Some(common.fee_payer),
+        allocations_csv: common.allocations_csv,
+        transaction_db: common.transaction_db,
+        transaction_log: common.transaction_log,
+        dollars_per_sol,
+        transfer_amount: common.transfer_amount,
+        dry_run: common.dry_run,
+        token_mint: None,
+        stake_args: Some(stake_args),
+        commitment: common.commitment,
+    })
+}
+
+fn parse_balances_args(matches: &ArgMatches) -> Result<BalancesArgs, Box<dyn Error>> {
+    let allocations_csv = value_t!(matches, "allocations_csv", String)?;
+    let token_mint = value_t!(matches, "token", Pubkey).ok();
+    let transfer_amount = value_t!(matches, "transfer_amount", f64).ok();
+    let dollars_per_sol = if token_mint.is_some() || transfer_amount.is_some() {
+        value_t!(matches, "dollars_per_sol", f64).unwrap_or(1.0)
+    } else {
+        value_t!(matches, "dollars_per_sol", f64)?
+    };
+
+    Ok(BalancesArgs {
+        allocations_csv,
+        dollars_per_sol,
+        token_mint,
+        transfer_amount,
+    })
+}
+
+pub fn resolve_command(matches: &ArgMatches) -> Result<Command, Box<dyn Error>> {
+    match matches.subcommand() {
+        ("distribute-tokens", Some(matches)) => {
+            Ok(Command::Distribute(parse_distribute_args(matches)?))
+        }
+        ("distribute-stake", Some(matches)) => {
+            Ok(Command::Distribute(parse_distribute_stake_args(matches)?))
+        }
+        ("balances", Some(matches)) => Ok(Command::Balances(parse_balances_args(matches)?)),
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,217 @@
+use crate::thin_client::NetworkClient;
+use indicatif::{ProgressBar, ProgressStyle};
+use solana_sdk::{commitment_config::CommitmentConfig, hash::Hash, signature::Signature};
+use solana_transaction_status::TransactionStatus;
+use std::{thread::sleep, time::Duration};
+
+/// The RPC limits `getSignatureStatuses` to this many signatures per call.
+pub const MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS: usize = 256;
+
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Outcome of waiting for one sent transaction to land.
+pub struct Confirmation {
+    pub finalized: bool,
+    pub confirmation_slot: Option<u64>,
+}
+
+/// Retries a transient RPC failure indefinitely rather than aborting the run; a flaky
+/// status poll should never be the reason already-sent transactions go unconfirmed.
+fn get_signature_statuses_with_retry<T: NetworkClient>(
+    client: &T,
+    signatures: &[Signature],
+) -> Vec<Option<TransactionStatus>> {
+    loop {
+        match client.get_signature_statuses(signatures) {
+            Ok(statuses) => return statuses,
+            Err(err) => {
+                eprintln!("getSignatureStatuses failed, retrying: {}", err);
+                sleep(CONFIRMATION_POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+fn is_blockhash_valid_with_retry<T: NetworkClient>(
+    client: &T,
+    blockhash: &Hash,
+    commitment: CommitmentConfig,
+) -> bool {
+    loop {
+        match client.is_blockhash_valid(blockhash, commitment) {
+            Ok(valid) => return valid,
+            Err(err) => {
+                eprintln!("isBlockhashValid failed, retrying: {}", err);
+                sleep(CONFIRMATION_POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// Polls `getSignatureStatuses` until every transaction in `sent` has either reached
+/// `commitment` or its blockhash has expired, reporting progress on `progress_bar`.
+pub fn confirm_transactions<T: NetworkClient>(
+    client: &T,
+    sent: &[(Signature, Hash)],
+    commitment: CommitmentConfig,
+    progress_bar: &ProgressBar,
+) -> Vec<Confirmation> {
+    let mut confirmations: Vec<Confirmation> = sent
+        .iter()
+        .map(|_| Confirmation {
+            finalized: false,
+            confirmation_slot: None,
+        })
+        .collect();
+
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} confirmed"),
+    );
+    progress_bar.set_length(sent.len() as u64);
+
+    let mut pending: Vec<usize> = (0..sent.len()).collect();
+    while !pending.is_empty() {
+        for chunk in pending
+            .clone()
+            .chunks(MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS)
+        {
+            let signatures: Vec<Signature> = chunk.iter().map(|&i| sent[i].0).collect();
+            let statuses = get_signature_statuses_with_retry(client, &signatures);
+
+            for (i, status) in chunk.iter().copied().zip(statuses) {
+                let (_signature, blockhash) = &sent[i];
+                if let Some(status) = status {
+                    if status.satisfies_commitment(commitment) {
+                        confirmations[i].finalized = true;
+                        confirmations[i].confirmation_slot = Some(status.slot);
+                        pending.retain(|&p| p != i);
+                        progress_bar.inc(1);
+                    }
+                } else if !is_blockhash_valid_with_retry(client, blockhash, commitment) {
+                    // The blockhash expired before the transaction landed; leave it
+                    // unfinalized so the caller re-sends the allocation next run.
+                    pending.retain(|&p| p != i);
+                    progress_bar.inc(1);
+                }
+            }
+        }
+
+        if !pending.is_empty() {
+            sleep(CONFIRMATION_POLL_INTERVAL);
+        }
+    }
+    progress_bar.finish_and_clear();
+
+    confirmations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::client_error::ClientError;
+    use solana_sdk::{account::Account, message::Message, pubkey::Pubkey, signature::Signer};
+    use solana_transaction_status::TransactionConfirmationStatus;
+    use std::{cell::RefCell, collections::HashMap};
+
+    /// A `NetworkClient` whose statuses and blockhash validity are fixed up front, so
+    /// `confirm_transactions` can be driven without an RPC endpoint.
+    struct MockClient {
+        statuses: RefCell<HashMap<Signature, Option<TransactionStatus>>>,
+        valid_blockhashes: HashMap<Hash, bool>,
+    }
+
+    impl NetworkClient for MockClient {
+        fn send_message(
+            &self,
+            _message: Message,
+            _signers: &[&dyn Signer],
+        ) -> Result<(Signature, Hash), ClientError> {
+            unimplemented!("not exercised by these tests")
+        }
+
+        fn get_account(&self, _pubkey: &Pubkey) -> Result<Option<Account>, ClientError> {
+            unimplemented!("not exercised by these tests")
+        }
+
+        fn get_minimum_balance_for_rent_exemption(
+            &self,
+            _data_len: usize,
+        ) -> Result<u64, ClientError> {
+            unimplemented!("not exercised by these tests")
+        }
+
+        fn get_signature_statuses(
+            &self,
+            signatures: &[Signature],
+        ) -> Result<Vec<Option<TransactionStatus>>, ClientError> {
+            let statuses = self.statuses.borrow();
+            Ok(signatures
+                .iter()
+                .map(|signature| statuses.get(signature).cloned().flatten())
+                .collect())
+        }
+
+        fn is_blockhash_valid(
+            &self,
+            blockhash: &Hash,
+            _commitment: CommitmentConfig,
+        ) -> Result<bool, ClientError> {
+            Ok(*self.valid_blockhashes.get(blockhash).unwrap_or(&false))
+        }
+
+        fn get_multiple_accounts(
+            &self,
+            _pubkeys: &[Pubkey],
+        ) -> Result<Vec<Option<Account>>, ClientError> {
+            unimplemented!("not exercised by these tests")
+        }
+    }
+
+    fn finalized_status(slot: u64) -> TransactionStatus {
+        TransactionStatus {
+            slot,
+            confirmations: None,
+            status: Ok(()),
+            err: None,
+            confirmation_status: Some(TransactionConfirmationStatus::Finalized),
+        }
+    }
+
+    #[test]
+    fn distinguishes_expired_from_finalized_transactions() {
+        let expired_signature = Signature::new(&[1; 64]);
+        let expired_blockhash = Hash::new(&[1; 32]);
+        let finalized_signature = Signature::new(&[2; 64]);
+        let finalized_blockhash = Hash::new(&[2; 32]);
+
+        let mut statuses = HashMap::new();
+        statuses.insert(expired_signature, None);
+        statuses.insert(finalized_signature, Some(finalized_status(55)));
+
+        let mut valid_blockhashes = HashMap::new();
+        valid_blockhashes.insert(expired_blockhash, false);
+        valid_blockhashes.insert(finalized_blockhash, true);
+
+        let client = MockClient {
+            statuses: RefCell::new(statuses),
+            valid_blockhashes,
+        };
+
+        let sent = vec![
+            (expired_signature, expired_blockhash),
+            (finalized_signature, finalized_blockhash),
+        ];
+        let confirmations = confirm_transactions(
+            &client,
+            &sent,
+            CommitmentConfig::finalized(),
+            &ProgressBar::hidden(),
+        );
+
+        assert!(!confirmations[0].finalized);
+        assert_eq!(confirmations[0].confirmation_slot, None);
+        assert!(confirmations[1].finalized);
+        assert_eq!(confirmations[1].confirmation_slot, Some(55));
+    }
+}
@@ -0,0 +1,79 @@
+use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::Path;
+
+/// A sent transaction, keyed by signature in the underlying db. Crash-safe and
+/// incrementally written, unlike the CSV log this replaced.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionInfo {
+    pub recipient: String,
+    pub amount: f64,
+    pub signature: String,
+    /// Stake account funded by this transaction, if this was a stake distribution.
+    pub stake_account: Option<String>,
+    /// Whether the transaction reached the configured commitment level.
+    pub finalized: bool,
+    pub confirmation_slot: Option<u64>,
+    /// Blockhash the transaction was built against; once this expires unconfirmed,
+    /// the allocation is safe to re-send.
+    pub last_valid_blockhash: String,
+    pub timestamp: i64,
+}
+
+pub fn open_db(db_path: &str) -> Result<PickleDb, Box<dyn Error>> {
+    if Path::new(db_path).exists() {
+        Ok(PickleDb::load(
+            db_path,
+            PickleDbDumpPolicy::AutoDump,
+            SerializationMethod::Json,
+        )?)
+    } else {
+        Ok(PickleDb::new(
+            db_path,
+            PickleDbDumpPolicy::AutoDump,
+            SerializationMethod::Json,
+        ))
+    }
+}
+
+pub fn set_transaction_info(
+    db: &mut PickleDb,
+    info: &TransactionInfo,
+) -> Result<(), Box<dyn Error>> {
+    db.set(&info.signature, info)?;
+    Ok(())
+}
+
+/// `PickleDb::iter()` walks entries in its own internal (hash) order, not the order
+/// transactions were submitted in, so callers must not rely on the order of the
+/// returned `Vec` — match entries by `recipient`, not by position.
+pub fn read_transaction_infos(db: &PickleDb) -> Vec<TransactionInfo> {
+    db.iter()
+        .filter_map(|kv| kv.get_value::<TransactionInfo>())
+        .collect()
+}
+
+/// The old append-only transaction log's column layout, kept for humans who want to
+/// eyeball a run without the bookkeeping fields the db tracks internally.
+#[derive(Serialize)]
+struct TransactionLogRow<'a> {
+    recipient: &'a str,
+    amount: f64,
+    signature: &'a str,
+}
+
+/// Dumps the db to a CSV at `csv_path`, preserving the column layout of the old
+/// append-only transaction log, for humans who want to eyeball a run.
+pub fn dump_to_csv(db: &PickleDb, csv_path: &str) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_path(csv_path)?;
+    for info in read_transaction_infos(db) {
+        wtr.serialize(TransactionLogRow {
+            recipient: &info.recipient,
+            amount: info.amount,
+            signature: &info.signature,
+        })?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
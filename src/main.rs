@@ -1,148 +1,307 @@
+mod allocation;
 mod arg_parser;
 mod args;
+mod confirmations;
+mod db;
 mod thin_client;
 
+use crate::allocation::{
+    allocations_from_bids, allocations_from_recipients, display_amount, parse_typed_allocations,
+    Bid, Recipient, TypedAllocation,
+};
 use crate::arg_parser::parse_args;
-use crate::args::{resolve_command, Command, DistributeArgs};
+use crate::args::{resolve_command, BalancesArgs, Command, DistributeArgs, StakeArgs};
+use crate::confirmations::confirm_transactions;
+use crate::db::TransactionInfo;
 use crate::thin_client::{NetworkClient, ThinClient};
+use chrono::Utc;
 use console::style;
 use csv::{ReaderBuilder, Trim};
-use serde::{Deserialize, Serialize};
+use indicatif::ProgressBar;
+use pickledb::PickleDb;
 use solana_cli_config::Config;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
+    hash::Hash,
     message::Message,
-    native_token::sol_to_lamports,
-    signature::{Signature, Signer},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    stake::{
+        instruction as stake_instruction,
+        state::{Authorized, Lockup, StakeAuthorize, StakeState},
+    },
     system_instruction,
 };
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
+use spl_token::solana_program::program_pack::Pack;
+use spl_token::{instruction::transfer_checked, state::Mint};
 use std::env;
 use std::error::Error;
-use std::fs;
-use std::path::Path;
-
-#[derive(Deserialize, Debug, Clone)]
-struct Bid {
-    bid_amount_dollars: f64,
-    primary_address: String,
-}
 
-struct Allocation {
-    recipient: String,
-    amount: f64,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct TransactionInfo {
-    recipient: String,
-    amount: f64,
-    signature: String,
+fn mint_decimals<T: NetworkClient>(
+    client: &ThinClient<T>,
+    mint: &Pubkey,
+) -> Result<u8, allocation::Error> {
+    let mint_account = client
+        .get_account(mint)
+        .unwrap()
+        .ok_or(allocation::Error::BadMint { input: *mint })?;
+    Mint::unpack(&mint_account.data)
+        .map(|mint| mint.decimals)
+        .map_err(|_| allocation::Error::BadMint { input: *mint })
 }
 
+// Matches by recipient, not position: `read_transaction_infos` makes no ordering
+// guarantee, and a positional fill would attribute one recipient's finalized payment
+// to whichever allocation happened to be in that slot.
 fn apply_previous_transactions(
-    allocations: &mut Vec<Allocation>,
+    allocations: &mut Vec<TypedAllocation>,
     transaction_infos: &[TransactionInfo],
+    decimals: Option<u8>,
 ) {
     for transaction_info in transaction_infos {
-        let mut amount = transaction_info.amount;
-        for allocation in allocations.iter_mut() {
+        if !transaction_info.finalized {
+            // Never landed (or we don't know yet); re-send it on this run.
+            continue;
+        }
+        let recipient: Pubkey = match transaction_info.recipient.parse() {
+            Ok(recipient) => recipient,
+            Err(_) => continue,
+        };
+        let mut amount = allocation::raw_amount(transaction_info.amount, decimals);
+        for allocation in allocations
+            .iter_mut()
+            .filter(|allocation| allocation.recipient == recipient)
+        {
+            if amount == 0 {
+                break;
+            }
             if allocation.amount >= amount {
                 allocation.amount -= amount;
-                break;
+                amount = 0;
             } else {
                 amount -= allocation.amount;
-                allocation.amount = 0.0;
+                allocation.amount = 0;
             }
         }
     }
-    allocations.retain(|x| x.amount > 0.0);
+    allocations.retain(|x| x.amount > 0);
 }
 
-fn create_allocation(bid: &Bid, dollars_per_sol: f64) -> Allocation {
-    Allocation {
-        recipient: bid.primary_address.clone(),
-        amount: bid.bid_amount_dollars / dollars_per_sol,
+fn token_transfer_message<T: NetworkClient>(
+    client: &ThinClient<T>,
+    from: &Pubkey,
+    mint: &Pubkey,
+    decimals: u8,
+    allocation: &TypedAllocation,
+) -> Message {
+    let sender_token_account = get_associated_token_address(from, mint);
+    let to = allocation.recipient;
+    let recipient_token_account = get_associated_token_address(&to, mint);
+
+    let mut instructions = vec![];
+    if client
+        .get_account(&recipient_token_account)
+        .unwrap()
+        .is_none()
+    {
+        instructions.push(create_associated_token_account(from, &to, mint));
     }
+
+    instructions.push(
+        transfer_checked(
+            &spl_token::id(),
+            &sender_token_account,
+            mint,
+            &recipient_token_account,
+            from,
+            &[],
+            allocation.amount,
+            decimals,
+        )
+        .unwrap(),
+    );
+    Message::new(&instructions)
 }
-fn distribute_tokens<T: NetworkClient>(
-    client: &ThinClient<T>,
-    allocations: &[Allocation],
-    args: &DistributeArgs<Box<dyn Signer>>,
-) -> Vec<Signature> {
-    let messages: Vec<Message> = allocations
-        .iter()
-        .map(|allocation| {
-            let from = args.sender_keypair.as_ref().unwrap().pubkey();
-            let to = allocation.recipient.parse().unwrap();
-            let lamports = sol_to_lamports(allocation.amount);
-            let instruction = system_instruction::transfer(&from, &to, lamports);
-            Message::new(&[instruction])
+
+// Stake accounts are created authorized to `from` so that `from` can sign the delegation
+// in the same message, then handed off to the real stake/withdraw authorities.
+fn stake_allocation_message(
+    from: &Pubkey,
+    stake_args: &StakeArgs,
+    stake_pubkey: &Pubkey,
+    allocation: &TypedAllocation,
+    rent_exempt_reserve: u64,
+) -> Message {
+    let lockup = allocation
+        .lockup_date
+        .map(|unlock_date| Lockup {
+            unix_timestamp: unlock_date.timestamp(),
+            ..Lockup::default()
         })
-        .collect();
+        .unwrap_or_default();
+    let authorized = Authorized {
+        staker: *from,
+        withdrawer: *from,
+    };
+    let lamports = allocation.amount + rent_exempt_reserve;
 
-    let signers = vec![
-        &**args.sender_keypair.as_ref().unwrap(),
-        &**args.fee_payer.as_ref().unwrap(),
-    ];
+    let mut instructions =
+        stake_instruction::create_account(from, stake_pubkey, &authorized, &lockup, lamports);
+    instructions.push(stake_instruction::delegate_stake(
+        stake_pubkey,
+        from,
+        &stake_args.vote_account,
+    ));
+    instructions.push(stake_instruction::authorize(
+        stake_pubkey,
+        from,
+        &stake_args.stake_authority,
+        StakeAuthorize::Staker,
+        None,
+    ));
+    instructions.push(stake_instruction::authorize(
+        stake_pubkey,
+        from,
+        &stake_args.withdraw_authority,
+        StakeAuthorize::Withdrawer,
+        None,
+    ));
+    Message::new(&instructions)
+}
 
-    messages
-        .into_iter()
-        .map(|message| client.send_message(message, &signers).unwrap())
-        .collect()
+/// Records a just-sent transaction as unconfirmed, so a crash between broadcasting and
+/// polling for confirmation still leaves a record of the broadcast in the db. The next
+/// run does not re-check such a record's status: it counts as not landed, and its
+/// allocation is sent again.
+fn record_sent_transaction(
+    transaction_db: &mut PickleDb,
+    allocation: &TypedAllocation,
+    decimals: Option<u8>,
+    signature: Signature,
+    blockhash: Hash,
+    stake_account: Option<Pubkey>,
+    timestamp: i64,
+) -> Result<(), Box<dyn Error>> {
+    db::set_transaction_info(
+        transaction_db,
+        &TransactionInfo {
+            recipient: allocation.recipient.to_string(),
+            amount: display_amount(allocation.amount, decimals),
+            signature: signature.to_string(),
+            stake_account: stake_account.map(|pubkey| pubkey.to_string()),
+            finalized: false,
+            confirmation_slot: None,
+            last_valid_blockhash: blockhash.to_string(),
+            timestamp,
+        },
+    )
 }
 
-fn append_transaction_infos(
-    allocations: &[Allocation],
-    signatures: &[Signature],
-    transactions_csv: &str,
-) -> Result<(), csv::Error> {
-    let existed = Path::new(&transactions_csv).exists();
-    if existed {
-        let transactions_bak = format!("{}.bak", &transactions_csv);
-        fs::copy(&transactions_csv, transactions_bak)?;
+fn distribute_tokens<T: NetworkClient>(
+    client: &ThinClient<T>,
+    allocations: &[TypedAllocation],
+    decimals: Option<u8>,
+    args: &DistributeArgs<Box<dyn Signer>>,
+    transaction_db: &mut PickleDb,
+) -> Result<Vec<(Signature, Hash, Option<Pubkey>)>, Box<dyn Error>> {
+    let sender_keypair = args.sender_keypair.as_ref().unwrap();
+    let fee_payer = args.fee_payer.as_ref().unwrap();
+    let from = sender_keypair.pubkey();
+    let timestamp = Utc::now().timestamp();
+
+    if let Some(stake_args) = &args.stake_args {
+        let rent_exempt_reserve = client
+            .get_minimum_balance_for_rent_exemption(StakeState::size_of())
+            .unwrap();
+        let mut results = Vec::with_capacity(allocations.len());
+        for allocation in allocations {
+            let stake_keypair = Keypair::new();
+            let message = stake_allocation_message(
+                &from,
+                stake_args,
+                &stake_keypair.pubkey(),
+                allocation,
+                rent_exempt_reserve,
+            );
+            let signers: Vec<&dyn Signer> = vec![&**sender_keypair, &**fee_payer, &stake_keypair];
+            let (signature, blockhash) = client.send_message(message, &signers).unwrap();
+            let stake_account = Some(stake_keypair.pubkey());
+            record_sent_transaction(
+                transaction_db,
+                allocation,
+                decimals,
+                signature,
+                blockhash,
+                stake_account,
+                timestamp,
+            )?;
+            results.push((signature, blockhash, stake_account));
+        }
+        return Ok(results);
     }
-    let file = fs::OpenOptions::new()
-        .create_new(!existed)
-        .write(true)
-        .append(existed)
-        .open(&transactions_csv)?;
-    let mut wtr = csv::WriterBuilder::new()
-        .has_headers(!existed)
-        .from_writer(file);
-
-    for (i, allocation) in allocations.iter().enumerate() {
-        let transaction_info = TransactionInfo {
-            recipient: allocation.recipient.clone(),
-            amount: allocation.amount,
-            signature: signatures[i].to_string(),
+
+    let signers: Vec<&dyn Signer> = vec![&**sender_keypair, &**fee_payer];
+    let mut results = Vec::with_capacity(allocations.len());
+    for allocation in allocations {
+        let message = if let Some(mint) = &args.token_mint {
+            let decimals = decimals.expect("decimals must be known for a token distribution");
+            token_transfer_message(client, &from, mint, decimals, allocation)
+        } else {
+            let instruction =
+                system_instruction::transfer(&from, &allocation.recipient, allocation.amount);
+            Message::new(&[instruction])
         };
-        wtr.serialize(transaction_info)?;
+        let (signature, blockhash) = client.send_message(message, &signers).unwrap();
+        record_sent_transaction(
+            transaction_db,
+            allocation,
+            decimals,
+            signature,
+            blockhash,
+            None,
+            timestamp,
+        )?;
+        results.push((signature, blockhash, None));
     }
-    wtr.flush()?;
-    Ok(())
+    Ok(results)
+}
+
+fn read_bids(allocations_csv: &str) -> Result<Vec<Bid>, csv::Error> {
+    let mut rdr = ReaderBuilder::new()
+        .trim(Trim::All)
+        .from_path(allocations_csv)?;
+    rdr.deserialize().collect()
+}
+
+fn read_recipients(allocations_csv: &str) -> Result<Vec<Recipient>, csv::Error> {
+    let mut rdr = ReaderBuilder::new()
+        .trim(Trim::All)
+        .from_path(allocations_csv)?;
+    rdr.deserialize().collect()
 }
 
 fn process_distribute<T: NetworkClient>(
     client: &ThinClient<T>,
     args: &DistributeArgs<Box<dyn Signer>>,
-) -> Result<(), csv::Error> {
-    let mut rdr = ReaderBuilder::new()
-        .trim(Trim::All)
-        .from_path(&args.allocations_csv)?;
-    let mut allocations: Vec<Allocation> = rdr
-        .deserialize()
-        .map(|bid| create_allocation(&bid.unwrap(), args.dollars_per_sol))
-        .collect();
-
-    let transaction_infos: Vec<TransactionInfo> = if Path::new(&args.transactions_csv).exists() {
-        let mut state_rdr = ReaderBuilder::new()
-            .trim(Trim::All)
-            .from_path(&args.transactions_csv)?;
-        state_rdr.deserialize().map(|x| x.unwrap()).collect()
+) -> Result<(), Box<dyn Error>> {
+    let allocations = if let Some(amount) = args.transfer_amount {
+        let recipients = read_recipients(&args.allocations_csv)?;
+        allocations_from_recipients(&recipients, amount)
     } else {
-        vec![]
+        let bids = read_bids(&args.allocations_csv)?;
+        allocations_from_bids(&bids, args.dollars_per_sol, args.token_mint.is_some())
     };
-    apply_previous_transactions(&mut allocations, &transaction_infos);
+    let decimals = args
+        .token_mint
+        .as_ref()
+        .map(|mint| mint_decimals(client, mint))
+        .transpose()?;
+    let mut allocations = parse_typed_allocations(&allocations, decimals)?;
+
+    let mut transaction_db = db::open_db(&args.transaction_db)?;
+    let transaction_infos = db::read_transaction_infos(&transaction_db);
+    apply_previous_transactions(&mut allocations, &transaction_infos, decimals);
 
     if allocations.is_empty() {
         eprintln!("No work to do");
@@ -154,12 +313,116 @@ fn process_distribute<T: NetworkClient>(
         style(format!("{:<44}  {}", "Recipient", "Amount")).bold()
     );
     for allocation in &allocations {
-        println!("{:<44}  {}", allocation.recipient, allocation.amount);
+        println!(
+            "{:<44}  {}",
+            allocation.recipient,
+            display_amount(allocation.amount, decimals)
+        );
     }
 
     if !args.dry_run {
-        let signatures = distribute_tokens(&client, &allocations, &args);
-        append_transaction_infos(&allocations, &signatures, &args.transactions_csv)?;
+        let results = distribute_tokens(client, &allocations, decimals, args, &mut transaction_db)?;
+        let sent: Vec<(Signature, Hash)> = results.iter().map(|(s, h, _)| (*s, *h)).collect();
+        let progress_bar = ProgressBar::new(sent.len() as u64);
+        let confirmations = confirm_transactions(client, &sent, args.commitment, &progress_bar);
+
+        let timestamp = Utc::now().timestamp();
+        for ((allocation, (signature, blockhash, stake_account)), confirmation) in
+            allocations.iter().zip(&results).zip(&confirmations)
+        {
+            let transaction_info = TransactionInfo {
+                recipient: allocation.recipient.to_string(),
+                amount: display_amount(allocation.amount, decimals),
+                signature: signature.to_string(),
+                stake_account: stake_account.map(|pubkey| pubkey.to_string()),
+                finalized: confirmation.finalized,
+                confirmation_slot: confirmation.confirmation_slot,
+                last_valid_blockhash: blockhash.to_string(),
+                timestamp,
+            };
+            db::set_transaction_info(&mut transaction_db, &transaction_info)?;
+        }
+
+        if let Some(transaction_log) = &args.transaction_log {
+            db::dump_to_csv(&transaction_db, transaction_log)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The RPC limits `getMultipleAccounts` to this many pubkeys per call.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+fn process_balances<T: NetworkClient>(
+    client: &ThinClient<T>,
+    args: &BalancesArgs,
+) -> Result<(), Box<dyn Error>> {
+    let allocations = if let Some(amount) = args.transfer_amount {
+        let recipients = read_recipients(&args.allocations_csv)?;
+        allocations_from_recipients(&recipients, amount)
+    } else {
+        let bids = read_bids(&args.allocations_csv)?;
+        allocations_from_bids(&bids, args.dollars_per_sol, args.token_mint.is_some())
+    };
+    let decimals = args
+        .token_mint
+        .as_ref()
+        .map(|mint| mint_decimals(client, mint))
+        .transpose()?;
+    let allocations = parse_typed_allocations(&allocations, decimals)?;
+
+    // Kept in native units (lamports, or raw SPL amount) so a match against `expected`
+    // is an exact integer comparison; reconstructing both sides as `f64` first would
+    // flag exact matches as mismatches once the amounts exceed a few SOL/UI units.
+    let mut actual_amounts: Vec<u64> = Vec::with_capacity(allocations.len());
+    for allocations_chunk in allocations.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let pubkeys: Vec<Pubkey> = allocations_chunk
+            .iter()
+            .map(|allocation| match &args.token_mint {
+                Some(mint) => get_associated_token_address(&allocation.recipient, mint),
+                None => allocation.recipient,
+            })
+            .collect();
+        let accounts = client.get_multiple_accounts(&pubkeys).unwrap();
+        actual_amounts.extend(accounts.into_iter().map(|account| {
+            match account {
+                Some(account) => match decimals {
+                    // A recipient's derived ATA address holding a non-token account is an
+                    // audit finding, not a reason to abort the whole command.
+                    Some(_) => spl_token::state::Account::unpack(&account.data)
+                        .map(|token_account| token_account.amount)
+                        .unwrap_or(0),
+                    None => account.lamports,
+                },
+                None => 0,
+            }
+        }));
+    }
+
+    println!(
+        "{}",
+        style(format!(
+            "{:<44}  {:>16}  {:>16}  {:>16}",
+            "Recipient", "Expected", "Actual", "Difference"
+        ))
+        .bold()
+    );
+    for (allocation, actual) in allocations.iter().zip(actual_amounts) {
+        let expected = display_amount(allocation.amount, decimals);
+        let actual_display = display_amount(actual, decimals);
+        let row = format!(
+            "{:<44}  {:>16.9}  {:>16.9}  {:>16.9}",
+            allocation.recipient,
+            expected,
+            actual_display,
+            actual_display - expected
+        );
+        if actual == allocation.amount {
+            println!("{}", row);
+        } else {
+            println!("{}", style(row).red());
+        }
     }
 
     Ok(())
@@ -176,6 +439,80 @@ fn main() -> Result<(), Box<dyn Error>> {
         Command::Distribute(args) => {
             process_distribute(&client, &args)?;
         }
+        Command::Balances(args) => {
+            process_balances(&client, &args)?;
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocation(recipient: Pubkey, amount: u64) -> TypedAllocation {
+        TypedAllocation {
+            recipient,
+            amount,
+            lockup_date: None,
+        }
+    }
+
+    fn transaction_info(recipient: Pubkey, amount: f64, finalized: bool) -> TransactionInfo {
+        TransactionInfo {
+            recipient: recipient.to_string(),
+            amount,
+            signature: Signature::default().to_string(),
+            stake_account: None,
+            finalized,
+            confirmation_slot: None,
+            last_valid_blockhash: Hash::default().to_string(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn expired_transaction_is_resent() {
+        let recipient = Pubkey::new_unique();
+        let mut allocations = vec![allocation(recipient, 10_000_000_000)];
+        let transaction_infos = vec![transaction_info(recipient, 10.0, false)];
+
+        apply_previous_transactions(&mut allocations, &transaction_infos, None);
+
+        assert_eq!(allocations.len(), 1);
+        assert_eq!(allocations[0].amount, 10_000_000_000);
+    }
+
+    #[test]
+    fn finalized_transaction_is_subtracted() {
+        let recipient = Pubkey::new_unique();
+        let mut allocations = vec![allocation(recipient, 10_000_000_000)];
+        let transaction_infos = vec![transaction_info(recipient, 10.0, true)];
+
+        apply_previous_transactions(&mut allocations, &transaction_infos, None);
+
+        assert!(allocations.is_empty());
+    }
+
+    #[test]
+    fn partial_finalization_resumes_only_the_unpaid_recipient() {
+        let paid = Pubkey::new_unique();
+        let unpaid = Pubkey::new_unique();
+        let mut allocations = vec![
+            allocation(paid, 10_000_000_000),
+            allocation(unpaid, 10_000_000_000),
+        ];
+        // `read_transaction_infos` makes no ordering guarantee, so list the unpaid
+        // recipient's (still-pending) info first to make sure matching is by
+        // recipient, not position.
+        let transaction_infos = vec![
+            transaction_info(unpaid, 10.0, false),
+            transaction_info(paid, 10.0, true),
+        ];
+
+        apply_previous_transactions(&mut allocations, &transaction_infos, None);
+
+        assert_eq!(allocations.len(), 1);
+        assert_eq!(allocations[0].recipient, unpaid);
+    }
+}
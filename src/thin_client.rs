@@ -0,0 +1,127 @@
+use solana_client::client_error::ClientError;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account, commitment_config::CommitmentConfig, hash::Hash, message::Message,
+    pubkey::Pubkey, signature::Signature, signature::Signer, transaction::Transaction,
+};
+use solana_transaction_status::TransactionStatus;
+
+/// Abstraction over the bits of `RpcClient` that distribution, confirmation and the
+/// balances check need, so that
+/// tests can swap in a mock network without spinning up a validator.
+pub trait NetworkClient {
+    /// Sends `message` without waiting for confirmation; returns the signature and the
+    /// blockhash the transaction was built against, so callers can later tell whether it
+    /// is still eligible to land.
+    fn send_message(
+        &self,
+        message: Message,
+        signers: &[&dyn Signer],
+    ) -> Result<(Signature, Hash), ClientError>;
+    /// Returns `None` rather than an error when the account does not exist yet.
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, ClientError>;
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> Result<u64, ClientError>;
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> Result<Vec<Option<TransactionStatus>>, ClientError>;
+    fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+        commitment: CommitmentConfig,
+    ) -> Result<bool, ClientError>;
+    /// Like `get_account`, but batched; `None` marks an account that doesn't exist.
+    fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> Result<Vec<Option<Account>>, ClientError>;
+}
+
+impl NetworkClient for RpcClient {
+    fn send_message(
+        &self,
+        message: Message,
+        signers: &[&dyn Signer],
+    ) -> Result<(Signature, Hash), ClientError> {
+        let blockhash = self.get_latest_blockhash()?;
+        let transaction = Transaction::new(signers, message, blockhash);
+        let signature = self.send_transaction(&transaction)?;
+        Ok((signature, blockhash))
+    }
+
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, ClientError> {
+        match RpcClient::get_account(self, pubkey) {
+            Ok(account) => Ok(Some(account)),
+            Err(err) if err.to_string().contains("AccountNotFound") => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> Result<u64, ClientError> {
+        RpcClient::get_minimum_balance_for_rent_exemption(self, data_len)
+    }
+
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> Result<Vec<Option<TransactionStatus>>, ClientError> {
+        Ok(RpcClient::get_signature_statuses(self, signatures)?.value)
+    }
+
+    fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+        commitment: CommitmentConfig,
+    ) -> Result<bool, ClientError> {
+        RpcClient::is_blockhash_valid(self, blockhash, commitment)
+    }
+
+    fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> Result<Vec<Option<Account>>, ClientError> {
+        RpcClient::get_multiple_accounts(self, pubkeys)
+    }
+}
+
+pub struct ThinClient<T: NetworkClient>(pub T);
+
+impl<T: NetworkClient> NetworkClient for ThinClient<T> {
+    fn send_message(
+        &self,
+        message: Message,
+        signers: &[&dyn Signer],
+    ) -> Result<(Signature, Hash), ClientError> {
+        self.0.send_message(message, signers)
+    }
+
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, ClientError> {
+        self.0.get_account(pubkey)
+    }
+
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> Result<u64, ClientError> {
+        self.0.get_minimum_balance_for_rent_exemption(data_len)
+    }
+
+    fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> Result<Vec<Option<TransactionStatus>>, ClientError> {
+        self.0.get_signature_statuses(signatures)
+    }
+
+    fn is_blockhash_valid(
+        &self,
+        blockhash: &Hash,
+        commitment: CommitmentConfig,
+    ) -> Result<bool, ClientError> {
+        self.0.is_blockhash_valid(blockhash, commitment)
+    }
+
+    fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> Result<Vec<Option<Account>>, ClientError> {
+        self.0.get_multiple_accounts(pubkeys)
+    }
+}